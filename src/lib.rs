@@ -19,33 +19,86 @@ use core::mem;
 use core::ptr;
 use libc::{size_t, c_void};
 
+/// The alignment that GC_malloc_uncollectable (and the collector's other allocation primitives)
+/// guarantees by default, without going through GC_posix_memalign.
+const DEFAULT_GC_ALIGN: usize = mem::size_of::<usize>();
+
+/// The collector has no uncollectable-and-aligned allocation primitive, so over-aligned
+/// allocations from the allocator hooks are built by hand: over-allocate with
+/// GC_malloc_uncollectable, bump the returned pointer up to the next `align` boundary, and stash
+/// the original (base) pointer in a header word immediately before the aligned address so that
+/// `__rust_deallocate`/`__rust_reallocate` can recover it for `GC_free`. This keeps
+/// over-aligned Rust allocations just as uncollectable-and-rooted as every other allocator-hook
+/// allocation, matching the invariant documented at the top of this crate.
+unsafe fn allocate_aligned_uncollectable(size: usize, align: usize) -> *mut u8 {
+    let header = mem::size_of::<usize>();
+    let raw = sys::GC_malloc_uncollectable((size + align + header) as size_t) as *mut u8;
+    if raw.is_null() {
+        return ptr::null_mut();
+    }
+    let data_min = raw as usize + header;
+    let aligned = (data_min + align - 1) & !(align - 1);
+    *((aligned - header) as *mut usize) = raw as usize;
+    aligned as *mut u8
+}
+
+/// Recovers the base pointer that `allocate_aligned_uncollectable` originally got back from
+/// GC_malloc_uncollectable, given the aligned pointer it handed out.
+unsafe fn base_of_aligned_uncollectable(ptr: *mut u8) -> *mut u8 {
+    let header = mem::size_of::<usize>();
+    *((ptr as usize - header) as *const usize) as *mut u8
+}
+
 /// This implementation of __rust_allocate invokes GC_malloc_uncollectable,
 /// which allocates memory that is not collectable by the garbage collector
 /// but is capable of rooting GC'd pointers. Any pointer that resides
 /// in memory allocated by Rust's allocator will be traced for pointers,
 /// and any pointers that are contained within this memory are considered
 /// to be rooted.
+///
+/// When `align` exceeds what GC_malloc_uncollectable guarantees, this goes through
+/// `allocate_aligned_uncollectable` instead, which preserves the same uncollectable-and-rooted
+/// guarantee for over-aligned allocations.
 #[no_mangle]
-pub extern "C" fn __rust_allocate(size: usize, _: usize) -> *mut u8 {
+pub extern "C" fn __rust_allocate(size: usize, align: usize) -> *mut u8 {
+    if align > DEFAULT_GC_ALIGN {
+        return unsafe { allocate_aligned_uncollectable(size, align) };
+    }
     unsafe { sys::GC_malloc_uncollectable(size as size_t) as *mut u8 }
 }
 
-/// Deallocates memory allocated by GC_malloc_uncollectable. This memory isn't normally
-/// collectable so we rely on Rust's drop glue to free the memory that it's allocated. Luckily,
-/// it's really good at that sort of thing!
+/// Deallocates memory allocated by __rust_allocate. Over-aligned allocations are recovered back
+/// to the base pointer that GC_malloc_uncollectable returned before being freed.
 #[no_mangle]
-pub extern "C" fn __rust_deallocate(ptr: *mut u8, _: usize, _: usize) {
-    unsafe { sys::GC_free(ptr as *mut c_void) }
+pub extern "C" fn __rust_deallocate(ptr: *mut u8, _: usize, align: usize) {
+    unsafe {
+        if align > DEFAULT_GC_ALIGN {
+            sys::GC_free(base_of_aligned_uncollectable(ptr) as *mut c_void);
+        } else {
+            sys::GC_free(ptr as *mut c_void);
+        }
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn __rust_reallocate(ptr: *mut u8, _: usize, size: usize, _: usize) -> *mut u8 {
+pub extern "C" fn __rust_reallocate(ptr: *mut u8, old_size: usize, size: usize, align: usize) -> *mut u8 {
+    if align > DEFAULT_GC_ALIGN {
+        unsafe {
+            let new_ptr = allocate_aligned_uncollectable(size, align);
+            if new_ptr.is_null() {
+                return ptr::null_mut();
+            }
+            ptr::copy_nonoverlapping(ptr, new_ptr, core::cmp::min(old_size, size));
+            sys::GC_free(base_of_aligned_uncollectable(ptr) as *mut c_void);
+            return new_ptr;
+        }
+    }
     unsafe { sys::GC_realloc(ptr as *mut c_void, size as size_t) as *mut u8 }
 }
 
 #[no_mangle]
-pub extern "C" fn __rust_reallocate_inplace(ptr: *mut u8, _: usize, size: usize, _: usize) -> *mut u8 {
-    unsafe { sys::GC_realloc(ptr as *mut c_void, size as size_t) as *mut u8 }
+pub extern "C" fn __rust_reallocate_inplace(ptr: *mut u8, old_size: usize, size: usize, align: usize) -> *mut u8 {
+    __rust_reallocate(ptr, old_size, size, align)
 }
 
 #[no_mangle]
@@ -61,6 +114,98 @@ pub fn gc_allocate(size: usize) -> *mut u8 {
     unsafe { sys::GC_malloc(size as size_t) as *mut u8 }
 }
 
+/// Allocates `size` bytes on the managed heap for data that is known to contain no GC'd
+/// pointers, such as byte buffers, strings, or numeric arrays. This memory is tracked and
+/// collected by the garbage collector just like `gc_allocate` output, but the collector will
+/// never scan its contents for pointers. This both speeds up marking and avoids false
+/// retention caused by bit patterns that happen to resemble heap addresses.
+///
+/// Callers are responsible for ensuring that the returned memory never holds a GC'd pointer;
+/// storing one here means the collector may free the object it points to while it is still
+/// reachable.
+#[inline]
+pub fn gc_allocate_atomic(size: usize) -> *mut u8 {
+    unsafe { sys::GC_malloc_atomic(size as size_t) as *mut u8 }
+}
+
+/// Allocates `size` bytes on the managed heap, aligned to `align` bytes, and returns a pointer
+/// to the newly-allocated memory. Like `gc_allocate`, this memory is collectable and traced by
+/// the garbage collector. Unlike `gc_allocate`, it can guarantee alignment stronger than the
+/// collector's default granule, which is required to store SIMD types, cache-line-aligned
+/// structures, or anything else needing `align` greater than a machine word.
+///
+/// `align` must be a power of two and a multiple of `size_of::<usize>()`. Returns a null
+/// pointer if the underlying allocation fails or if `align` does not meet those requirements.
+#[inline]
+pub fn gc_allocate_aligned(size: usize, align: usize) -> *mut u8 {
+    unsafe {
+        let mut out: *mut c_void = ptr::null_mut();
+        if sys::GC_posix_memalign(&mut out, align as size_t, size as size_t) != 0 {
+            return ptr::null_mut();
+        }
+        out as *mut u8
+    }
+}
+
+/// A packed description of which machine words in an object hold traceable GC pointers,
+/// produced by `gc_make_descriptor`. Allocating with a `GcTypeDescriptor` via `gc_allocate_typed`
+/// tells the collector to trace exactly those words during marking and treat everything else as
+/// opaque data, which eliminates the false retention that conservative scanning can cause for
+/// mixed pointer/scalar structs.
+#[derive(Clone, Copy, Debug)]
+pub struct GcTypeDescriptor(sys::GC_descr);
+
+/// Packs `pointer_word_bitmap` into `words`, one bit per entry, least-significant-bit first
+/// within each word. This is the bit order that the collector's `GC_make_descriptor` and its
+/// internal bit-test helper expect: bit `i % bits_per_word` of `words[i / bits_per_word]`
+/// describes object-word `i`.
+fn pack_bitmap(pointer_word_bitmap: &[bool], words: &mut [sys::GC_word]) {
+    let bits_per_word = mem::size_of::<sys::GC_word>() * 8;
+    for (i, &is_pointer) in pointer_word_bitmap.iter().enumerate() {
+        if is_pointer {
+            words[i / bits_per_word] |= 1 << (i % bits_per_word);
+        }
+    }
+}
+
+/// Builds a `GcTypeDescriptor` from a bitmap of which machine words in an object are pointers.
+/// `pointer_word_bitmap` must have one entry per machine word of the object (rounding the
+/// object's size up to word granularity), with `true` marking a word that holds a traceable GC
+/// pointer and `false` marking opaque data that the collector should skip during marking.
+///
+/// Returns `None` if the scratch buffer used to build the bitmap could not be allocated.
+#[inline]
+pub fn gc_make_descriptor(pointer_word_bitmap: &[bool]) -> Option<GcTypeDescriptor> {
+    let bits_per_word = mem::size_of::<sys::GC_word>() * 8;
+    let word_count = (pointer_word_bitmap.len() + bits_per_word - 1) / bits_per_word;
+    if word_count == 0 {
+        // Nothing to pack; skip the scratch allocation entirely rather than handing
+        // GC_make_descriptor a pointer we can't guarantee is non-null for a zero-length slice.
+        return Some(unsafe { GcTypeDescriptor(sys::GC_make_descriptor(ptr::null(), 0)) });
+    }
+    unsafe {
+        // GC_make_descriptor copies the bitmap it is given, so the scratch buffer only needs to
+        // live for the duration of this call; it does not need to be GC-visible memory.
+        let bitmap = libc::calloc(word_count as size_t, mem::size_of::<sys::GC_word>() as size_t) as *mut sys::GC_word;
+        if bitmap.is_null() {
+            return None;
+        }
+        let words = core::slice::from_raw_parts_mut(bitmap, word_count);
+        pack_bitmap(pointer_word_bitmap, words);
+        let descr = sys::GC_make_descriptor(bitmap, word_count as size_t);
+        libc::free(bitmap as *mut c_void);
+        Some(GcTypeDescriptor(descr))
+    }
+}
+
+/// Allocates `size` bytes on the managed heap, tracing only the words that `descr` marks as
+/// holding pointers. The bitmap that `descr` was built from must cover at least `size` rounded
+/// up to word granularity; any word past the end of the bitmap is treated as opaque data.
+#[inline]
+pub fn gc_allocate_typed(size: usize, descr: GcTypeDescriptor) -> *mut u8 {
+    unsafe { sys::GC_malloc_explicitly_typed(size as size_t, descr.0) as *mut u8 }
+}
+
 /// Forces the garbage collector to run, deallocating any unreachable memory. This is a full,
 /// stop-the-world collection.
 #[inline]
@@ -68,6 +213,35 @@ pub fn gc_collect() {
     unsafe { sys::GC_gcollect(); }
 }
 
+/// Switches the collector into incremental (generational) mode, which uses the operating
+/// system's dirty-page tracking to mark mostly-unchanged pages only once, spreading collection
+/// work across many small pauses instead of the single long pause that `gc_collect` forces.
+/// This should be called once, early in the program's lifetime, before any significant
+/// allocation has taken place.
+#[inline]
+pub fn gc_enable_incremental() {
+    unsafe { sys::GC_enable_incremental() }
+}
+
+/// Performs a bounded increment of incremental collection work and returns whether more work
+/// remains before the current collection cycle completes. Latency-sensitive programs that have
+/// called `gc_enable_incremental` should call this periodically at a safe point instead of
+/// `gc_collect`, to avoid triggering a long global pause.
+#[inline]
+pub fn gc_collect_a_little() -> bool {
+    unsafe { sys::GC_collect_a_little() != 0 }
+}
+
+/// Returns whether the incremental collector would like dirty-page protection (e.g. mprotect
+/// write faults) enabled on the platform's virtual memory to track which pages have been
+/// touched since the last increment. Some platforms can run incremental collection without it;
+/// this lets a caller that has enabled incremental mode tell whether it's getting the cheaper,
+/// fault-driven dirty tracking or falling back to a more expensive scan.
+#[inline]
+pub fn gc_incremental_protection_needed() -> bool {
+    unsafe { sys::GC_incremental_protection_needs() != 0 }
+}
+
 /// Used as an argument to register_finalizer to influence the circumstances upon which the garbage
 /// collector will run finalizers.
 #[derive(Clone, Copy, Debug)]
@@ -138,6 +312,27 @@ pub fn register_finalizer(ptr: *mut u8,
     }
 }
 
+/// By default, the Boehm GC invokes finalizers itself during or immediately after a collection,
+/// which means finalizer code runs with GC internals in a sensitive state and cannot safely
+/// allocate or take locks. Calling this function switches the collector into buffered mode:
+/// ready-to-run finalizers are queued instead of being invoked immediately, and the host
+/// application must drain the queue by calling `gc_run_pending_finalizers` at a point of its
+/// choosing, such as the top of an event loop. This decouples destructor execution from the
+/// marking phase and makes it safe for finalizers to allocate.
+#[inline]
+pub fn gc_enable_buffered_finalizers() {
+    unsafe { sys::GC_set_finalize_on_demand(1) }
+}
+
+/// Runs all finalizers that are currently queued to run and returns how many were invoked.
+/// This should be called at a safe point by applications that have called
+/// `gc_enable_buffered_finalizers`; calling it without having done so is harmless but
+/// unnecessary, since finalizers are run automatically in the default mode.
+#[inline]
+pub fn gc_run_pending_finalizers() -> usize {
+    unsafe { sys::GC_invoke_finalizers() as usize }
+}
+
 /// Returns the number of bytes in the managed heap, including empty blocks
 /// and fragmentation loss.
 #[inline]
@@ -163,6 +358,45 @@ pub fn total_bytes() -> usize {
     unsafe { sys::GC_get_total_bytes() as usize }
 }
 
+/// A bundle of the collector's heap and collection counters, modeled on OCaml's `Gc.stat`
+/// record. Grouping these into one struct spares a profiler from making five separate accessor
+/// calls and keeping each call's result around, and lets it compute collection
+/// frequency and allocation rate (such as collections-per-second or bytes-per-collection) from a
+/// single return value.
+///
+/// This is a convenience, not a guarantee: `gc_stats` reads each counter with its own
+/// `GC_get_*` call, the same as calling `heap_size`, `free_bytes`, etc. individually, so a
+/// collection can still run between two of those reads on a multi-threaded collector. Boehm
+/// does not expose a single call that reads all of these atomically.
+#[derive(Clone, Copy, Debug)]
+pub struct GcStats {
+    /// The number of bytes in the managed heap, including empty blocks and fragmentation loss.
+    pub heap_size: usize,
+    /// A lower bound on the number of free bytes in the heap.
+    pub free_bytes: usize,
+    /// The number of bytes allocated since the last collection.
+    pub bytes_since_gc: usize,
+    /// The total number of bytes allocated in this process.
+    pub total_bytes: usize,
+    /// The number of collections that have taken place so far.
+    pub collections: usize,
+}
+
+/// Reads the collector's heap size and collection activity counters into one struct. See
+/// `GcStats` for why this isn't an atomic snapshot.
+#[inline]
+pub fn gc_stats() -> GcStats {
+    unsafe {
+        GcStats {
+            heap_size: sys::GC_get_heap_size() as usize,
+            free_bytes: sys::GC_get_free_bytes() as usize,
+            bytes_since_gc: sys::GC_get_bytes_since_gc() as usize,
+            total_bytes: sys::GC_get_total_bytes() as usize,
+            collections: sys::GC_get_gc_no() as usize,
+        }
+    }
+}
+
 /// Enables the garbage collector, if the number of times that gc_enable() has been
 /// called is the same as the number of times that gc_disable() has been called.
 #[inline]
@@ -176,6 +410,31 @@ pub fn gc_disable() {
     unsafe { sys::GC_disable() }
 }
 
+/// Sets a hard ceiling on the size of the managed heap. Once the heap has grown to this size,
+/// further allocations that would require growing it past the ceiling invoke the OOM handler
+/// (see `set_oom_fn`) instead of expanding the heap further. A value of `0` removes the limit,
+/// which is the collector's default.
+#[inline]
+pub fn set_max_heap_size(size: usize) {
+    unsafe { sys::GC_set_max_heap_size(size as size_t) }
+}
+
+/// Sets the free space divisor, which controls how aggressively the collector reclaims memory.
+/// Larger values cause the collector to run more often and keep the heap smaller at the cost of
+/// throughput; smaller values favor throughput by growing the heap and collecting less often.
+#[inline]
+pub fn set_free_space_divisor(value: usize) {
+    unsafe { sys::GC_set_free_space_divisor(value as size_t) }
+}
+
+/// Pre-grows the heap by `size` bytes. This is useful immediately before a known burst of large
+/// allocations, to avoid paying for one or more collections that would otherwise be triggered
+/// while the heap is still growing to accommodate them. Returns `true` on success.
+#[inline]
+pub fn expand_heap(size: usize) -> bool {
+    unsafe { sys::GC_expand_hp(size as size_t) != 0 }
+}
+
 /// Sets the function that the GC calls when all available memory is exhausted.
 /// For now, this function must not return. The Boehm GC /does/ allow the function
 /// to return, but it must return either null or a previously-allocated heap object.
@@ -185,3 +444,28 @@ pub fn gc_disable() {
 pub fn set_oom_fn(oom_fn: extern "C" fn(size_t) -> *mut u8) {
     unsafe { sys::GC_oom_fn = mem::transmute(oom_fn); }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads bit `i` out of a packed bitmap using the same least-significant-bit-first
+    /// convention that `GC_make_descriptor`'s internal bit-test helper uses, so this test fails
+    /// if `pack_bitmap` ever packs the bits in the wrong order again.
+    fn get_bit(words: &[sys::GC_word], i: usize) -> bool {
+        let bits_per_word = mem::size_of::<sys::GC_word>() * 8;
+        (words[i / bits_per_word] >> (i % bits_per_word)) & 1 == 1
+    }
+
+    #[test]
+    fn pack_bitmap_is_lsb_first() {
+        // A mixed pointer/scalar layout (pointer, scalar, pointer, pointer, scalar) that fits in
+        // a single GC_word on every platform this crate targets.
+        let layout = [true, false, true, true, false];
+        let mut words = [0 as sys::GC_word; 1];
+        pack_bitmap(&layout, &mut words);
+        for (i, &expected) in layout.iter().enumerate() {
+            assert_eq!(get_bit(&words, i), expected, "word {} packed incorrectly", i);
+        }
+    }
+}
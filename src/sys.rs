@@ -1,14 +1,26 @@
-use libc::{size_t, c_void};
+use libc::{size_t, c_void, c_int};
+
+/// A single machine word, matching the collector's `GC_word` typedef. Pointer-bitmaps and
+/// type descriptors are built up out of these.
+#[allow(non_camel_case_types)]
+pub type GC_word = usize;
+
+/// An opaque, packed description of which words in an object hold traceable pointers, as
+/// produced by `GC_make_descriptor`.
+#[allow(non_camel_case_types)]
+pub type GC_descr = GC_word;
 
 #[link(name = "gc")]
 extern {
     pub static mut GC_oom_fn : extern "C" fn(size_t) -> !;
 
     pub fn GC_malloc(nbytes: size_t) -> *mut c_void;
+    pub fn GC_malloc_atomic(nbytes: size_t) -> *mut c_void;
     pub fn GC_malloc_uncollectable(nbytes: size_t) -> *mut c_void;
     pub fn GC_realloc(old: *mut c_void, new_size: size_t) -> *mut c_void;
     pub fn GC_free(dead: *mut c_void);
     pub fn GC_gcollect();
+    pub fn GC_posix_memalign(mem_ptr: *mut *mut c_void, align: size_t, nbytes: size_t) -> c_int;
     pub fn GC_register_finalizer_ignore_self(ptr: *mut c_void,
                                              finalizer: extern "C" fn(*mut c_void, *mut c_void),
                                              client_data: *mut c_void,
@@ -28,6 +40,17 @@ extern {
     pub fn GC_get_free_bytes() -> size_t;
     pub fn GC_get_bytes_since_gc() -> size_t;
     pub fn GC_get_total_bytes() -> size_t;
+    pub fn GC_get_gc_no() -> size_t;
     pub fn GC_disable();
     pub fn GC_enable();
+    pub fn GC_set_finalize_on_demand(value: c_int);
+    pub fn GC_invoke_finalizers() -> c_int;
+    pub fn GC_enable_incremental();
+    pub fn GC_incremental_protection_needs() -> c_int;
+    pub fn GC_collect_a_little() -> c_int;
+    pub fn GC_set_max_heap_size(size: size_t);
+    pub fn GC_set_free_space_divisor(value: size_t);
+    pub fn GC_expand_hp(size: size_t) -> c_int;
+    pub fn GC_make_descriptor(bitmap: *const GC_word, len: size_t) -> GC_descr;
+    pub fn GC_malloc_explicitly_typed(nbytes: size_t, d: GC_descr) -> *mut c_void;
 }